@@ -0,0 +1,574 @@
+use core::cell::{Cell, RefCell};
+use core::cmp::Ordering;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use std::collections::{BinaryHeap, VecDeque};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use slab::Slab;
+
+use widestring::u16cstr;
+
+use winapi::shared::winerror::{WAIT_OBJECT_0, WAIT_TIMEOUT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::processthreadsapi::{GetCurrentThread, GetCurrentThreadId};
+use winapi::um::winbase::WAIT_FAILED;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::{MsgWaitForMultipleObjectsEx, RegisterWindowMessageW, MWMO_INPUTAVAILABLE, QS_ALLINPUT};
+
+use crate::executor_handle::ExecutorHandle;
+use crate::shard::{self, Shard};
+use crate::waker;
+
+/// `MsgWaitForMultipleObjectsEx` caps a wait at `MAXIMUM_WAIT_OBJECTS` (64)
+/// handles, one of which it reserves internally to represent "a message is
+/// available". Handles registered beyond this are sharded off to auxiliary
+/// threads (see the `shard` module).
+const PRIMARY_CAPACITY: usize = 63;
+
+pub type HandleId = usize;
+
+pub type TaskId = usize;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()>>>;
+type ReadyQueue = Rc<RefCell<VecDeque<TaskId>>>;
+
+struct Task {
+    future: BoxFuture,
+    waker: Waker,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<LocalExecutor>>> = RefCell::new(None);
+}
+
+/// Spawns a `!Send` future onto the executor currently driving the calling
+/// thread's `block_on` loop.
+///
+/// # Panics
+///
+/// Panics if called from outside a `block_on` call.
+pub fn spawn(fut: impl Future<Output = ()> + 'static) {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let executor = current.as_ref().expect("spawn() called outside of block_on");
+        executor.spawn(fut);
+    });
+}
+
+/// Registers `waker` to be woken once `deadline` passes, returning a flag
+/// the caller should set to cancel the timer and a cell the caller should
+/// update each poll so the most recently handed-out waker is the one that
+/// fires. Used by `timer::Sleep`.
+///
+/// # Panics
+///
+/// Panics if called from outside a `block_on` call.
+pub(crate) fn schedule_timer(deadline: Instant, waker: Waker) -> (Rc<Cell<bool>>, Rc<RefCell<Waker>>) {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let executor = current.as_ref().expect("sleep() called outside of block_on");
+        executor.schedule_timer(deadline, waker)
+    })
+}
+
+/// Returns a `Send`able handle that other threads can use to spawn work
+/// onto the executor currently driving the calling thread's `block_on` loop.
+///
+/// # Panics
+///
+/// Panics if called from outside a `block_on` call.
+pub fn handle() -> ExecutorHandle {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        let executor = current.as_ref().expect("handle() called outside of block_on");
+        executor.handle()
+    })
+}
+
+/// Returns the executor currently driving the calling thread's `block_on`
+/// loop, for subsystems (like `handle::AsyncHandle`) that need to hold onto
+/// it past the call that looked it up.
+///
+/// # Panics
+///
+/// Panics if called from outside a `block_on` call.
+pub(crate) fn current() -> Rc<LocalExecutor> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .clone()
+            .expect("wait_for() called outside of block_on")
+    })
+}
+
+/// Installs `executor` as the thread's current executor for the duration of
+/// the returned guard's lifetime, so that `spawn()` can find it.
+pub(crate) fn enter(executor: &Rc<LocalExecutor>) -> EnterGuard {
+    CURRENT.with(|current| *current.borrow_mut() = Some(executor.clone()));
+    EnterGuard
+}
+
+pub(crate) struct EnterGuard;
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| *current.borrow_mut() = None);
+    }
+}
+
+/// A single-threaded executor that runs any number of `!Send` tasks
+/// alongside the future driven directly by `block_on`, from the same Win32
+/// message loop.
+pub(crate) struct LocalExecutor {
+    tasks: RefCell<Slab<Task>>,
+    ready: ReadyQueue,
+    notify: Rc<Waker>,
+    timers: RefCell<BinaryHeap<TimerEntry>>,
+    handles: RefCell<Slab<HandleEntry>>,
+    shards: RefCell<Vec<Shard>>,
+    shard_signals: Arc<Mutex<VecDeque<HandleId>>>,
+    thread_id: u32,
+    alive: Arc<AtomicBool>,
+}
+
+impl LocalExecutor {
+    pub fn new() -> Self {
+        LocalExecutor {
+            tasks: RefCell::new(Slab::new()),
+            ready: Rc::new(RefCell::new(VecDeque::new())),
+            notify: Rc::new(waker::for_current_thread()),
+            timers: RefCell::new(BinaryHeap::new()),
+            handles: RefCell::new(Slab::new()),
+            shards: RefCell::new(Vec::new()),
+            shard_signals: Arc::new(Mutex::new(VecDeque::new())),
+            thread_id: unsafe { GetCurrentThreadId() },
+            alive: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// A `Send`able handle other threads can use to spawn work onto this
+    /// executor. Must be called from the executor's own thread.
+    pub fn handle(&self) -> ExecutorHandle {
+        ExecutorHandle::new(unsafe { GetCurrentThread() }, self.alive.clone())
+    }
+
+    pub fn schedule_timer(&self, deadline: Instant, waker: Waker) -> (Rc<Cell<bool>>, Rc<RefCell<Waker>>) {
+        let cancelled = Rc::new(Cell::new(false));
+        let waker = Rc::new(RefCell::new(waker));
+
+        self.timers.borrow_mut().push(TimerEntry {
+            deadline,
+            waker: waker.clone(),
+            cancelled: cancelled.clone(),
+        });
+
+        (cancelled, waker)
+    }
+
+    /// How long the blocking wait should sleep for: the time until the
+    /// nearest live timer deadline, or `None` if there are no timers
+    /// pending (the caller should wait indefinitely).
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let mut timers = self.timers.borrow_mut();
+
+        while let Some(entry) = timers.peek() {
+            if entry.cancelled.get() {
+                timers.pop();
+                continue;
+            }
+
+            let now = Instant::now();
+            return Some(entry.deadline.saturating_duration_since(now));
+        }
+
+        None
+    }
+
+    /// Pops and wakes every timer whose deadline has passed. Cancelled
+    /// timers are dropped without waking.
+    pub fn fire_expired_timers(&self) {
+        let now = Instant::now();
+        let mut timers = self.timers.borrow_mut();
+
+        while let Some(entry) = timers.peek() {
+            if entry.cancelled.get() {
+                timers.pop();
+                continue;
+            }
+
+            if entry.deadline > now {
+                break;
+            }
+
+            let entry = timers.pop().unwrap();
+            entry.waker.borrow().wake_by_ref();
+        }
+    }
+
+    pub fn register_handle(&self, handle: HANDLE, waker: Waker) -> (HandleId, Rc<Cell<bool>>) {
+        let ready = Rc::new(Cell::new(false));
+
+        let id = self.handles.borrow_mut().insert(HandleEntry {
+            handle,
+            waker,
+            ready: ready.clone(),
+        });
+
+        self.rebuild_shards();
+
+        (id, ready)
+    }
+
+    pub fn deregister_handle(&self, id: HandleId) {
+        self.handles.borrow_mut().try_remove(id);
+        self.rebuild_shards();
+    }
+
+    /// Replaces the waker a still-pending `wait_for` wakes once `id` fires,
+    /// so the most recently polled task is the one woken rather than
+    /// whichever task first called `register_handle`.
+    pub fn update_handle_waker(&self, id: HandleId, waker: Waker) {
+        if let Some(entry) = self.handles.borrow_mut().get_mut(id) {
+            entry.waker = waker;
+        }
+    }
+
+    fn fire_handle(&self, id: HandleId) {
+        if let Some(entry) = self.handles.borrow_mut().try_remove(id) {
+            entry.ready.set(true);
+            entry.waker.wake();
+        }
+
+        self.rebuild_shards();
+    }
+
+    /// Reshards the overflow (beyond `PRIMARY_CAPACITY`) handles across
+    /// auxiliary wait threads: existing shards have their member list
+    /// updated in place via `set_members`, new shards are spawned if the
+    /// overflow grew, and surplus shards are torn down if it shrank.
+    fn rebuild_shards(&self) {
+        let overflow: Vec<(HandleId, HANDLE)> = self
+            .handles
+            .borrow()
+            .iter()
+            .skip(PRIMARY_CAPACITY)
+            .map(|(id, entry)| (id, entry.handle))
+            .collect();
+
+        let mut shards = self.shards.borrow_mut();
+        let chunks = shard::chunk_overflow(&overflow, PRIMARY_CAPACITY);
+
+        for (shard, chunk) in shards.iter().zip(chunks.iter()) {
+            shard.set_members(chunk.clone());
+        }
+
+        if chunks.len() > shards.len() {
+            for chunk in &chunks[shards.len()..] {
+                shards.push(Shard::spawn(
+                    chunk.clone(),
+                    self.shard_signals.clone(),
+                    self.thread_id,
+                    shard_message_id(),
+                ));
+            }
+        } else {
+            shards.truncate(chunks.len());
+        }
+    }
+
+    /// Wakes every handle that an auxiliary shard thread reported as fired
+    /// since the last call. Called when `wait` reports a pending message
+    /// that turns out to be the shard-signal message.
+    pub fn drain_shard_signals(&self) {
+        let fired: Vec<HandleId> = {
+            let mut signals = self.shard_signals.lock().unwrap();
+            signals.drain(..).collect()
+        };
+
+        for id in fired {
+            self.fire_handle(id);
+        }
+    }
+
+    pub fn is_shard_message(&self, msg: u32) -> bool {
+        msg == shard_message_id()
+    }
+
+    /// Blocks until a Win32 message is available, a registered handle fires,
+    /// or `timeout_ms` elapses. Firing a handle is handled internally (the
+    /// caller doesn't need to distinguish it from a timeout): both return
+    /// `WaitOutcome::Retry` so the main loop re-checks ready tasks and
+    /// timers before waiting again.
+    pub fn wait(&self, timeout_ms: u32) -> WaitOutcome {
+        let primary: Vec<(HandleId, HANDLE)> = self
+            .handles
+            .borrow()
+            .iter()
+            .take(PRIMARY_CAPACITY)
+            .map(|(id, entry)| (id, entry.handle))
+            .collect();
+
+        let handles: Vec<HANDLE> = primary.iter().map(|(_, handle)| *handle).collect();
+
+        let ret = unsafe {
+            MsgWaitForMultipleObjectsEx(
+                handles.len() as u32,
+                handles.as_ptr(),
+                timeout_ms,
+                QS_ALLINPUT,
+                MWMO_INPUTAVAILABLE,
+            )
+        };
+
+        if ret == WAIT_TIMEOUT {
+            return WaitOutcome::Retry;
+        }
+
+        if ret == WAIT_FAILED {
+            let error = unsafe { GetLastError() };
+            panic!("MsgWaitForMultipleObjectsEx failed: {error}");
+        }
+
+        let index = (ret - WAIT_OBJECT_0) as usize;
+
+        match primary.get(index) {
+            Some((id, _)) => {
+                self.fire_handle(*id);
+                WaitOutcome::Retry
+            }
+            None => WaitOutcome::Message,
+        }
+    }
+
+    pub fn spawn(&self, fut: impl Future<Output = ()> + 'static) {
+        self.spawn_boxed(Box::new(fut));
+    }
+
+    /// Like `spawn`, but takes an already-boxed future. Used to enqueue a
+    /// task reconstructed from an `ExecutorHandle::spawn` message without
+    /// boxing it a second time.
+    pub fn spawn_boxed(&self, fut: Box<dyn Future<Output = ()>>) {
+        let mut tasks = self.tasks.borrow_mut();
+        let id = tasks.vacant_key();
+        let waker = task_waker(id, self.ready.clone(), self.notify.clone());
+
+        tasks.insert(Task {
+            future: Box::into_pin(fut),
+            waker,
+        });
+
+        self.ready.borrow_mut().push_back(id);
+    }
+
+    pub fn has_ready(&self) -> bool {
+        !self.ready.borrow().is_empty()
+    }
+
+    /// Polls every task id currently in the ready queue exactly once.
+    ///
+    /// Tasks that wake themselves (or spawn new tasks) while being polled
+    /// are appended to the slab and ready queue as normal; they're picked up
+    /// on the caller's next call rather than this one, so a task can't
+    /// starve the message loop by waking itself forever from inside a
+    /// single `run_ready` call.
+    pub fn run_ready(&self) {
+        let batch: Vec<TaskId> = self.ready.borrow_mut().drain(..).collect();
+
+        for id in batch {
+            self.poll_task(id);
+        }
+    }
+
+    fn poll_task(&self, id: TaskId) {
+        // Grab a raw pointer to the task's future and drop the slab borrow
+        // before polling, so that a task which spawns another task (which
+        // needs its own `borrow_mut` on `tasks`) doesn't hit a `RefCell`
+        // panic, and so a task that happens to free its own slot (it can't
+        // today, but nothing stops a future holding `TaskId`) doesn't
+        // deadlock on itself.
+        let (future, waker) = {
+            let mut tasks = self.tasks.borrow_mut();
+
+            let Some(task) = tasks.get_mut(id) else {
+                // Woken after already running to completion; ignore.
+                return;
+            };
+
+            let future: *mut (dyn Future<Output = ()>) =
+                unsafe { Pin::into_inner_unchecked(task.future.as_mut()) };
+
+            (future, task.waker.clone())
+        };
+
+        let mut cx = Context::from_waker(&waker);
+
+        // SAFETY: `future` points into a `Pin<Box<_>>` owned by the slab
+        // entry for `id`; that entry is not touched again until we
+        // re-borrow `self.tasks` below, so this is the only live reference.
+        let poll = unsafe { Pin::new_unchecked(&mut *future).poll(&mut cx) };
+
+        if poll.is_ready() {
+            self.tasks.borrow_mut().remove(id);
+        }
+    }
+}
+
+impl Drop for LocalExecutor {
+    fn drop(&mut self) {
+        // Lets any `ExecutorHandle`s outstanding on other threads fail
+        // `spawn()` gracefully instead of posting into the void.
+        self.alive.store(false, AtomicOrdering::SeqCst);
+    }
+}
+
+struct HandleEntry {
+    handle: HANDLE,
+    waker: Waker,
+    ready: Rc<Cell<bool>>,
+}
+
+pub enum WaitOutcome {
+    /// The wait was satisfied by something the executor already handled
+    /// internally (a timer or handle firing); the caller should just loop
+    /// back around rather than dispatch a message.
+    Retry,
+    /// A Win32 message is available; the caller should pump it as usual.
+    Message,
+}
+
+fn shard_message_id() -> u32 {
+    static MSG_ID: OnceLock<u32> = OnceLock::new();
+
+    *MSG_ID.get_or_init(|| {
+        let name = u16cstr!("windows_executor::executor::shard_signal");
+        unsafe { RegisterWindowMessageW(name.as_ptr()) }
+    })
+}
+
+struct TimerEntry {
+    deadline: Instant,
+    waker: Rc<RefCell<Waker>>,
+    cancelled: Rc<Cell<bool>>,
+}
+
+// `BinaryHeap` is a max-heap; reverse the deadline comparison so the
+// earliest deadline sorts to the top.
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for TimerEntry {}
+
+struct TaskWaker {
+    id: TaskId,
+    ready: ReadyQueue,
+    notify: Rc<Waker>,
+}
+
+static TASK_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    task_clone,
+    task_wake,
+    task_wake_by_ref,
+    task_drop,
+);
+
+fn task_waker(id: TaskId, ready: ReadyQueue, notify: Rc<Waker>) -> Waker {
+    let data = Rc::new(TaskWaker { id, ready, notify });
+    unsafe { Waker::from_raw(RawWaker::new(Rc::into_raw(data) as *const (), &TASK_VTABLE)) }
+}
+
+unsafe fn task_clone(ptr: *const ()) -> RawWaker {
+    let data = Rc::from_raw(ptr as *const TaskWaker);
+    let cloned = Rc::clone(&data);
+    mem::forget(data);
+    RawWaker::new(Rc::into_raw(cloned) as *const (), &TASK_VTABLE)
+}
+
+unsafe fn task_wake(ptr: *const ()) {
+    task_wake_by_ref(ptr);
+    task_drop(ptr);
+}
+
+unsafe fn task_wake_by_ref(ptr: *const ()) {
+    let data = &*(ptr as *const TaskWaker);
+
+    // The task may have already run to completion and been removed from
+    // the slab; `poll_task` treats a missing id as a no-op, so pushing a
+    // stale id here is harmless.
+    data.ready.borrow_mut().push_back(data.id);
+    data.notify.wake_by_ref();
+}
+
+unsafe fn task_drop(ptr: *const ()) {
+    drop(Rc::from_raw(ptr as *const TaskWaker));
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::task::noop_waker;
+
+    use super::*;
+
+    fn entry(deadline: Instant) -> TimerEntry {
+        TimerEntry {
+            deadline,
+            waker: Rc::new(RefCell::new(noop_waker())),
+            cancelled: Rc::new(Cell::new(false)),
+        }
+    }
+
+    #[test]
+    fn timer_heap_pops_earliest_deadline_first() {
+        let base = Instant::now();
+        let mut heap = BinaryHeap::new();
+
+        heap.push(entry(base + Duration::from_millis(30)));
+        heap.push(entry(base + Duration::from_millis(10)));
+        heap.push(entry(base + Duration::from_millis(20)));
+
+        assert_eq!(heap.pop().unwrap().deadline, base + Duration::from_millis(10));
+        assert_eq!(heap.pop().unwrap().deadline, base + Duration::from_millis(20));
+        assert_eq!(heap.pop().unwrap().deadline, base + Duration::from_millis(30));
+        assert!(heap.pop().is_none());
+    }
+
+    #[test]
+    fn cancelled_timer_is_pruned_instead_of_firing() {
+        let executor = LocalExecutor::new();
+        let now = Instant::now();
+
+        let (cancelled, _waker) = executor.schedule_timer(now, noop_waker());
+        executor.schedule_timer(now + Duration::from_secs(60), noop_waker());
+
+        cancelled.set(true);
+
+        // The live timer a minute out is still the nearest thing left once
+        // the cancelled, already-due entry is pruned from the front.
+        let timeout = executor.next_timeout().unwrap();
+        assert!(timeout >= Duration::from_secs(59));
+
+        executor.fire_expired_timers();
+        assert_eq!(executor.timers.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,171 @@
+//! Lets other threads inject work onto a running executor, following the
+//! crate's usual "dedicated window-procedure thread" pattern: a duplicated
+//! handle to the target thread is captured once, a unique message is registered with
+//! `RegisterWindowMessageW`, and `block_on`'s loop recognizes it before
+//! dispatching.
+
+use core::future::Future;
+use core::fmt;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use widestring::u16cstr;
+
+use winapi::shared::minwindef::{LPARAM, WPARAM};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::GetThreadId;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::{PostThreadMessageW, RegisterWindowMessageW};
+
+use crate::waker;
+
+type BoxedTask = Box<dyn Future<Output = ()> + Send>;
+
+/// A `Send`able handle that lets other threads `spawn` a future onto this
+/// executor's thread.
+pub struct ExecutorHandle {
+    thread: HANDLE,
+    alive: Arc<AtomicBool>,
+}
+
+// SAFETY: `thread` is a duplicated HANDLE, independently owned by this
+// instance (see `waker::duplicate_handle`); `alive` is an `Arc<AtomicBool>`.
+// Neither has any thread affinity.
+unsafe impl Send for ExecutorHandle {}
+
+/// Returned by [`ExecutorHandle::spawn`] when the executor's `block_on` call
+/// has already returned. The future passed to `spawn` is dropped rather
+/// than leaked.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutorExited;
+
+impl fmt::Display for ExecutorExited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("executor's block_on call has already returned")
+    }
+}
+
+impl ExecutorHandle {
+    pub(crate) fn new(thread: HANDLE, alive: Arc<AtomicBool>) -> Self {
+        ExecutorHandle {
+            thread: unsafe { waker::duplicate_handle(thread) },
+            alive,
+        }
+    }
+
+    /// Boxes `fut` and posts it to the executor's thread to be polled
+    /// alongside its other tasks. Fails without running or dropping-on-the-
+    /// wrong-thread anything if the executor has already exited: the future
+    /// is simply dropped here, on the calling thread.
+    pub fn spawn(&self, fut: impl Future<Output = ()> + Send + 'static) -> Result<(), ExecutorExited> {
+        if !self.alive.load(Ordering::SeqCst) {
+            return Err(ExecutorExited);
+        }
+
+        let boxed: BoxedTask = Box::new(fut);
+
+        // `Box<dyn Future + Send>` is a fat pointer; box it again to get a
+        // plain, thin pointer we can smuggle through a thread message.
+        let ptr = Box::into_raw(Box::new(boxed)) as usize;
+
+        let thread_id = unsafe { GetThreadId(self.thread) };
+        if thread_id == 0 {
+            let error = unsafe { GetLastError() };
+            log::debug!("GetThreadId failed in ExecutorHandle::spawn: {error}");
+            drop_boxed_task(ptr);
+            return Err(ExecutorExited);
+        }
+
+        let (high, low) = split_ptr(ptr);
+        let rc = unsafe { PostThreadMessageW(thread_id, spawn_message_id(), high, low) };
+
+        if rc != 1 {
+            // The thread is gone, or otherwise unreachable; reclaim the
+            // task rather than leak it.
+            let error = unsafe { GetLastError() };
+            log::debug!("PostThreadMessageW failed in ExecutorHandle::spawn: {error}");
+            drop_boxed_task(ptr);
+            return Err(ExecutorExited);
+        }
+
+        Ok(())
+    }
+}
+
+impl Clone for ExecutorHandle {
+    fn clone(&self) -> Self {
+        ExecutorHandle {
+            thread: unsafe { waker::duplicate_handle(self.thread) },
+            alive: self.alive.clone(),
+        }
+    }
+}
+
+impl Drop for ExecutorHandle {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.thread) };
+    }
+}
+
+fn drop_boxed_task(ptr: usize) {
+    // SAFETY: `ptr` was just produced by `Box::into_raw` above and hasn't
+    // been passed anywhere else yet.
+    unsafe { drop(Box::from_raw(ptr as *mut BoxedTask)) };
+}
+
+/// Reconstructs and returns the boxed task a `spawn_message_id()` message
+/// carries, from the `(wParam, lParam)` halves of its pointer.
+///
+/// # Safety
+///
+/// `high`/`low` must be exactly the pair produced by `split_ptr` for a
+/// pointer obtained from `Box::into_raw` that hasn't been reconstructed
+/// already.
+pub(crate) unsafe fn reconstruct(high: WPARAM, low: LPARAM) -> BoxedTask {
+    let ptr = join_ptr(high, low) as *mut BoxedTask;
+    *Box::from_raw(ptr)
+}
+
+#[cfg(target_pointer_width = "64")]
+fn split_ptr(ptr: usize) -> (WPARAM, LPARAM) {
+    ((ptr >> 32) as WPARAM, (ptr & 0xFFFF_FFFF) as LPARAM)
+}
+
+#[cfg(target_pointer_width = "64")]
+fn join_ptr(high: WPARAM, low: LPARAM) -> usize {
+    ((high as usize) << 32) | (low as usize & 0xFFFF_FFFF)
+}
+
+#[cfg(target_pointer_width = "32")]
+fn split_ptr(ptr: usize) -> (WPARAM, LPARAM) {
+    (ptr as WPARAM, 0)
+}
+
+#[cfg(target_pointer_width = "32")]
+fn join_ptr(high: WPARAM, _low: LPARAM) -> usize {
+    high as usize
+}
+
+pub(crate) fn spawn_message_id() -> u32 {
+    static MSG_ID: OnceLock<u32> = OnceLock::new();
+
+    *MSG_ID.get_or_init(|| {
+        let name = u16cstr!("windows_executor::executor_handle::spawn");
+        unsafe { RegisterWindowMessageW(name.as_ptr()) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_join_ptr_round_trips() {
+        for ptr in [0usize, 0x42, usize::MAX, usize::MAX / 7] {
+            let (high, low) = split_ptr(ptr);
+            assert_eq!(join_ptr(high, low), ptr);
+        }
+    }
+}
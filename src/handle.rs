@@ -0,0 +1,73 @@
+use core::cell::Cell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use std::rc::{Rc, Weak};
+
+use winapi::um::winnt::HANDLE;
+
+use crate::executor::{self, HandleId, LocalExecutor};
+
+/// Waits for `handle` to become signaled: an auto- or manual-reset event, an
+/// exited process, or an overlapped I/O completion event.
+///
+/// `handle` must outlive the returned future; dropping the future before it
+/// resolves deregisters the wait, so a freed handle is never passed to the
+/// underlying wait call.
+pub fn wait_for(handle: HANDLE) -> AsyncHandle {
+    AsyncHandle {
+        handle,
+        registration: None,
+    }
+}
+
+struct Registration {
+    id: HandleId,
+    ready: Rc<Cell<bool>>,
+    // Weak, not Rc: a spawned task awaiting its own executor's handle would
+    // otherwise hold a strong reference back to that same executor (which
+    // owns the task), forming a cycle that keeps `LocalExecutor::drop` from
+    // ever running.
+    executor: Weak<LocalExecutor>,
+}
+
+/// Future returned by [`wait_for`].
+pub struct AsyncHandle {
+    handle: HANDLE,
+    registration: Option<Registration>,
+}
+
+impl Future for AsyncHandle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        match &this.registration {
+            Some(registration) if registration.ready.get() => Poll::Ready(()),
+            Some(registration) => {
+                if let Some(executor) = registration.executor.upgrade() {
+                    executor.update_handle_waker(registration.id, cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            None => {
+                let executor = executor::current();
+                let (id, ready) = executor.register_handle(this.handle, cx.waker().clone());
+                this.registration = Some(Registration { id, ready, executor: Rc::downgrade(&executor) });
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for AsyncHandle {
+    fn drop(&mut self) {
+        if let Some(registration) = self.registration.take() {
+            if let Some(executor) = registration.executor.upgrade() {
+                executor.deregister_handle(registration.id);
+            }
+        }
+    }
+}
@@ -1,5 +1,11 @@
 #![cfg_attr(not(test), no_std)]
 
+mod executor;
+mod executor_handle;
+mod handle;
+mod shard;
+pub mod sync;
+mod timer;
 mod waker;
 
 use core::future::Future;
@@ -7,9 +13,19 @@ use core::mem::MaybeUninit;
 use core::ptr;
 use core::task::{Context, Poll};
 
+use std::rc::Rc;
+
 use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winbase::INFINITE;
 use winapi::um::winuser::{DispatchMessageW, GetMessageW, TranslateMessage, MSG};
 
+use executor::WaitOutcome;
+
+pub use executor::{handle, spawn};
+pub use executor_handle::{ExecutorExited, ExecutorHandle};
+pub use handle::{wait_for, AsyncHandle};
+pub use timer::{sleep, sleep_until, Sleep};
+
 pub type LoopResult<T> = Result<T, ShouldExit>;
 
 #[derive(Debug, Clone, Copy)]
@@ -18,6 +34,9 @@ pub struct ShouldExit;
 pub fn block_on<T>(fut: impl Future<Output = T>) -> LoopResult<T> {
     futures::pin_mut!(fut);
 
+    let executor = Rc::new(executor::LocalExecutor::new());
+    let _guard = executor::enter(&executor);
+
     let waker = waker::for_current_thread();
     let mut context = Context::from_waker(&waker);
 
@@ -26,6 +45,26 @@ pub fn block_on<T>(fut: impl Future<Output = T>) -> LoopResult<T> {
             return Ok(value);
         }
 
+        if executor.has_ready() {
+            executor.run_ready();
+        }
+
+        // Recomputed every iteration: a timer spawned since the last wait
+        // may have a nearer deadline than whatever we last waited on.
+        let timeout = executor
+            .next_timeout()
+            .map(|remaining| {
+                // u32::MAX is bit-for-bit INFINITE, so clamp one below it or a
+                // deadline past ~49.7 days would wait forever instead of finitely.
+                u32::try_from(remaining.as_millis()).unwrap_or(u32::MAX).min(u32::MAX - 1)
+            })
+            .unwrap_or(INFINITE);
+
+        if let WaitOutcome::Retry = executor.wait(timeout) {
+            executor.fire_expired_timers();
+            continue;
+        }
+
         unsafe {
             let mut msg = MaybeUninit::<MSG>::uninit();
 
@@ -44,6 +83,23 @@ pub fn block_on<T>(fut: impl Future<Output = T>) -> LoopResult<T> {
             }
 
             let msg = msg.assume_init();
+
+            if executor.is_shard_message(msg.message) {
+                executor.drain_shard_signals();
+                continue;
+            }
+
+            if msg.message == sync::signal_message_id() {
+                sync::dispatch_signal(msg.wParam as u32);
+                continue;
+            }
+
+            if msg.message == executor_handle::spawn_message_id() {
+                let fut = executor_handle::reconstruct(msg.wParam, msg.lParam);
+                executor.spawn_boxed(fut);
+                continue;
+            }
+
             log::debug!("dispatching message: hwnd={:?}, msg={}", msg.hwnd, msg.message);
             TranslateMessage(&msg);
             DispatchMessageW(&msg);
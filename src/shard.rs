@@ -0,0 +1,207 @@
+//! `MsgWaitForMultipleObjectsEx` caps a single wait at `MAXIMUM_WAIT_OBJECTS`
+//! (64) handles, one of which the executor reserves for its own use (see
+//! `executor::PRIMARY_CAPACITY`). When more handles than that are registered,
+//! the overflow is sharded across auxiliary threads managed by this module:
+//! each owns up to `MAXIMUM_WAIT_OBJECTS - 1` real handles plus a
+//! manual-reset "refresh" event that the executor sets when the shard should
+//! reload its handle list, and notifies the executor thread via
+//! `PostThreadMessageW` when one of its handles fires.
+
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::WAIT_OBJECT_0;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::synchapi::{CreateEventW, ResetEvent, SetEvent, WaitForMultipleObjects};
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::PostThreadMessageW;
+
+use crate::executor::HandleId;
+
+struct SharedHandle(HANDLE);
+unsafe impl Send for SharedHandle {}
+
+/// Splits `overflow` into groups of at most `max_per_shard`, the same
+/// partitioning [`super::executor::LocalExecutor::rebuild_shards`] uses to
+/// decide each shard's membership.
+pub(crate) fn chunk_overflow(
+    overflow: &[(HandleId, HANDLE)],
+    max_per_shard: usize,
+) -> Vec<Vec<(HandleId, HANDLE)>> {
+    overflow
+        .chunks(max_per_shard)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
+
+/// One auxiliary wait thread, watching up to 63 of the overflow handles.
+pub(crate) struct Shard {
+    members: Arc<Mutex<Vec<(HandleId, HANDLE)>>>,
+    refresh: SharedHandle,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Shard {
+    /// Spawns a thread that waits on `members` (reloading them whenever
+    /// `set_members` is called), pushes the `HandleId` of any handle that
+    /// fires onto `fired`, and posts a `msg_id` thread message to
+    /// `main_thread_id`. The executor then drains `fired` (see
+    /// `LocalExecutor::drain_shard_signals`) to find out which are ready.
+    pub fn spawn(
+        members: Vec<(HandleId, HANDLE)>,
+        fired: Arc<Mutex<VecDeque<HandleId>>>,
+        main_thread_id: u32,
+        msg_id: u32,
+    ) -> Shard {
+        assert!(members.len() <= 63, "a shard can only own up to 63 handles");
+
+        let refresh = unsafe { CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null()) };
+
+        if refresh.is_null() {
+            let error = unsafe { GetLastError() };
+            panic!("CreateEventW failed: {error}");
+        }
+
+        let members = Arc::new(Mutex::new(members));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let members = members.clone();
+            let stop = stop.clone();
+            let refresh = SharedHandle(refresh);
+
+            thread::spawn(move || shard_thread(members, refresh, stop, fired, main_thread_id, msg_id))
+        };
+
+        Shard {
+            members,
+            refresh: SharedHandle(refresh),
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Replaces the set of handles this shard waits on, and wakes the shard
+    /// thread so it picks up the new set immediately.
+    pub fn set_members(&self, members: Vec<(HandleId, HANDLE)>) {
+        *self.members.lock().unwrap() = members;
+
+        let rc = unsafe { SetEvent(self.refresh.0) };
+        if rc != 1 {
+            let error = unsafe { GetLastError() };
+            log::debug!("SetEvent failed on shard refresh: {error}");
+        }
+    }
+}
+
+impl Drop for Shard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        let rc = unsafe { SetEvent(self.refresh.0) };
+        if rc != 1 {
+            let error = unsafe { GetLastError() };
+            log::debug!("SetEvent failed while stopping shard: {error}");
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        unsafe { CloseHandle(self.refresh.0) };
+    }
+}
+
+fn shard_thread(
+    members: Arc<Mutex<Vec<(HandleId, HANDLE)>>>,
+    refresh: SharedHandle,
+    stop: Arc<AtomicBool>,
+    fired: Arc<Mutex<VecDeque<HandleId>>>,
+    main_thread_id: u32,
+    msg_id: u32,
+) {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let snapshot: Vec<(HandleId, HANDLE)> = members.lock().unwrap().clone();
+
+        let mut handles: Vec<HANDLE> = snapshot.iter().map(|(_, handle)| *handle).collect();
+        handles.push(refresh.0);
+
+        let ret = unsafe {
+            WaitForMultipleObjects(handles.len() as u32, handles.as_ptr(), FALSE, u32::MAX)
+        };
+
+        let index = match ret.checked_sub(WAIT_OBJECT_0) {
+            Some(index) if (index as usize) < handles.len() => index as usize,
+            _ => {
+                let error = unsafe { GetLastError() };
+                log::debug!("WaitForMultipleObjects failed in shard thread: {error}");
+                continue;
+            }
+        };
+
+        // the refresh event is always the last handle in the array
+        if index == snapshot.len() {
+            unsafe { ResetEvent(refresh.0) };
+            continue;
+        }
+
+        let (id, _) = snapshot[index];
+        fired.lock().unwrap().push_back(id);
+
+        // Drop the fired handle from our own membership now, not just the
+        // main thread's eventual rebuild_shards/set_members round-trip --
+        // otherwise a handle that stays signaled (e.g. an exited process)
+        // would be re-observed as fired on every loop iteration, busy-spinning
+        // this thread until the main thread catches up.
+        members.lock().unwrap().retain(|&(hid, _)| hid != id);
+
+        let rc = unsafe { PostThreadMessageW(main_thread_id, msg_id, 0, 0) };
+        if rc != 1 {
+            let error = unsafe { GetLastError() };
+            log::debug!("PostThreadMessageW failed notifying shard signal: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflow(n: usize) -> Vec<(HandleId, HANDLE)> {
+        (0..n).map(|id| (id, id as HANDLE)).collect()
+    }
+
+    #[test]
+    fn chunk_overflow_splits_evenly() {
+        let chunks = chunk_overflow(&overflow(6), 3);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 3);
+        assert_eq!(chunks[1].len(), 3);
+    }
+
+    #[test]
+    fn chunk_overflow_leaves_a_remainder_in_its_own_chunk() {
+        let chunks = chunk_overflow(&overflow(7), 3);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].len(), 3);
+        assert_eq!(chunks[1].len(), 3);
+        assert_eq!(chunks[2].len(), 1);
+    }
+
+    #[test]
+    fn chunk_overflow_of_empty_input_is_empty() {
+        assert!(chunk_overflow(&overflow(0), 63).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+//! A multi-producer, single-consumer channel whose [`Receiver`] is a
+//! [`Stream`], so producers on other threads can feed an async consumer
+//! driven by this crate's message loop.
+//!
+//! Unlike [`super::message_window`] or [`super::signals`], nothing here is
+//! tied to the executor's thread at construction time: [`Sender::send`]
+//! wakes the stored [`Waker`] directly, which works correctly even when
+//! called from a foreign thread, because our wakers are built around a
+//! duplicated thread `HANDLE` and `PostThreadMessageW(WM_NULL)` (see the
+//! `waker` module).
+
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::Stream;
+
+struct State<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    // `queue` and `waker` live behind one lock so a `send` can never land
+    // between a receiver's queue check and it parking its waker: either the
+    // value is in the queue before the receiver locks, or the receiver's
+    // waker is stored before the sender locks, never both missed at once.
+    state: Mutex<State<T>>,
+    senders: AtomicUsize,
+}
+
+/// Creates a channel, returning its [`Sender`]/[`Receiver`] pair.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            waker: None,
+        }),
+        senders: AtomicUsize::new(1),
+    });
+
+    (
+        Sender { shared: shared.clone() },
+        Receiver { shared },
+    )
+}
+
+/// The sending half of a channel created by [`channel`]. Cloneable, and safe
+/// to send from any thread.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Pushes `value` onto the channel, waking the receiver if it's parked.
+    pub fn send(&self, value: T) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.queue.push_back(value);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::SeqCst);
+        Sender { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // We were the last sender; wake the receiver so it observes
+            // end-of-stream instead of parking forever.
+            if let Some(waker) = self.shared.state.lock().unwrap().waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// The receiving half of a channel created by [`channel`]. Implements
+/// [`Stream`], yielding `None` once every [`Sender`] has dropped.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(value) = state.queue.pop_front() {
+            return Poll::Ready(Some(value));
+        }
+
+        if self.shared.senders.load(Ordering::SeqCst) == 0 {
+            return Poll::Ready(None);
+        }
+
+        state.waker.replace(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::Poll;
+
+    use futures::task::noop_waker;
+    use futures::Stream;
+
+    use super::channel;
+
+    fn poll<T>(receiver: std::pin::Pin<&mut super::Receiver<T>>) -> Poll<Option<T>> {
+        let waker = noop_waker();
+        let mut cx = core::task::Context::from_waker(&waker);
+        receiver.poll_next(&mut cx)
+    }
+
+    #[test]
+    fn receives_in_order_from_multiple_senders() {
+        let (tx1, mut rx) = channel();
+        let tx2 = tx1.clone();
+
+        tx1.send(1);
+        tx2.send(2);
+        tx1.send(3);
+
+        let mut rx = std::pin::Pin::new(&mut rx);
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(Some(1)));
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(Some(2)));
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(Some(3)));
+    }
+
+    #[test]
+    fn yields_none_once_every_sender_is_dropped() {
+        let (tx1, mut rx) = channel();
+        let tx2 = tx1.clone();
+
+        drop(tx1);
+
+        let mut rx = std::pin::Pin::new(&mut rx);
+        assert_eq!(poll(rx.as_mut()), Poll::Pending);
+
+        drop(tx2);
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(None));
+    }
+
+    #[test]
+    fn pending_value_is_not_lost_behind_eof() {
+        let (tx, mut rx) = channel();
+
+        tx.send(42);
+        drop(tx);
+
+        let mut rx = std::pin::Pin::new(&mut rx);
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(Some(42)));
+        assert_eq!(poll(rx.as_mut()), Poll::Ready(None));
+    }
+}
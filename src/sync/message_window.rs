@@ -4,6 +4,7 @@ use core::pin::Pin;
 use core::ptr::{self, NonNull};
 use core::task::{Context, Poll, Waker};
 
+use std::collections::VecDeque;
 use std::sync::OnceLock;
 
 use futures::Stream;
@@ -21,6 +22,27 @@ pub trait FromMessage: Sized {
     unsafe fn from_message(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> Option<Self>;
 }
 
+/// What to do with an incoming message when a bounded [`MessageWindow`]'s
+/// queue is already full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Discard the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Discard the incoming message, keeping the queue as it was.
+    DropNewest,
+}
+
+/// How many messages a [`MessageWindow`] will buffer before applying its
+/// [`Overflow`] policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capacity {
+    /// Never drop a message; the queue grows to fit however many are
+    /// pending.
+    Unbounded,
+    /// Apply `overflow` once the queue holds `limit` messages.
+    Bounded { limit: usize, overflow: Overflow },
+}
+
 pub struct MessageWindow<Msg> {
     hwnd: HWND,
     inner: InnerPtr<Msg>,
@@ -29,17 +51,27 @@ pub struct MessageWindow<Msg> {
 type InnerPtr<Msg> = NonNull<RefCell<Inner<Msg>>>;
 
 struct Inner<Msg> {
-    message: Option<Msg>,
+    queue: VecDeque<Msg>,
     waker: Option<Waker>,
+    capacity: Capacity,
+    dropped: u64,
 }
 
 impl<Msg: FromMessage> MessageWindow<Msg> {
+    /// Creates a window with an unbounded message queue.
     pub fn new() -> Self {
+        Self::with_capacity(Capacity::Unbounded)
+    }
+
+    /// Creates a window whose message queue is subject to `capacity`.
+    pub fn with_capacity(capacity: Capacity) -> Self {
         let class = get_class::<Msg>();
 
         let inner = Box::new(RefCell::new(Inner {
-            message: None,
+            queue: VecDeque::new(),
             waker: None,
+            capacity,
+            dropped: 0,
         }));
 
         let inner = NonNull::new(Box::into_raw(inner)).unwrap();
@@ -78,6 +110,14 @@ impl<Msg> MessageWindow<Msg> {
     pub fn handle(&self) -> HWND {
         self.hwnd
     }
+
+    /// How many messages have been discarded due to the queue's [`Capacity`]
+    /// being exceeded. Only ever non-zero for a window created with
+    /// [`Capacity::Bounded`].
+    pub fn dropped(&self) -> u64 {
+        let inner = unsafe { self.inner.as_ref() };
+        inner.borrow().dropped
+    }
 }
 
 impl<Msg: FromMessage> Stream for MessageWindow<Msg> {
@@ -87,7 +127,7 @@ impl<Msg: FromMessage> Stream for MessageWindow<Msg> {
         let inner = unsafe { self.inner.as_ref() };
         let mut inner = inner.borrow_mut();
 
-        if let Some(msg) = inner.message.take() {
+        if let Some(msg) = inner.queue.pop_front() {
             return Poll::Ready(Some(msg));
         }
 
@@ -134,9 +174,7 @@ unsafe extern "system" fn wnd_proc<Msg: FromMessage>(
 
     let mut inner = inner.as_ref().borrow_mut();
 
-    if inner.message.replace(message).is_some() {
-        log::debug!("dropped previous message, not received on time");
-    }
+    enqueue(&mut inner, message);
 
     if let Some(waker) = inner.waker.take() {
         waker.wake();
@@ -145,6 +183,38 @@ unsafe extern "system" fn wnd_proc<Msg: FromMessage>(
     0
 }
 
+/// Applies `inner`'s [`Capacity`]/[`Overflow`] policy to an incoming
+/// message: pushes it if there's room, otherwise drops either it or the
+/// oldest queued message per `overflow`, bumping `inner.dropped` either way.
+fn enqueue<Msg>(inner: &mut Inner<Msg>, message: Msg) {
+    match inner.capacity {
+        Capacity::Unbounded => {
+            inner.queue.push_back(message);
+        }
+        Capacity::Bounded { limit, overflow } if inner.queue.len() >= limit => {
+            inner.dropped += 1;
+
+            match overflow {
+                Overflow::DropOldest => {
+                    log::debug!("message queue full, dropping oldest message");
+                    inner.queue.pop_front();
+                    // a zero-length queue has nothing to pop, so only push
+                    // back onto it if `limit` actually leaves room for one
+                    if limit > 0 {
+                        inner.queue.push_back(message);
+                    }
+                }
+                Overflow::DropNewest => {
+                    log::debug!("message queue full, dropping incoming message");
+                }
+            }
+        }
+        Capacity::Bounded { .. } => {
+            inner.queue.push_back(message);
+        }
+    }
+}
+
 unsafe fn get_inner_ptr<Msg>(hwnd: HWND) -> Option<InnerPtr<Msg>> {
     let ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA);
     InnerPtr::new(ptr as *mut _)
@@ -190,3 +260,96 @@ fn get_class<Msg: FromMessage>() -> &'static U16CStr {
 fn get_instance() -> *mut HINSTANCE__ {
     unsafe { GetModuleHandleW(ptr::null()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(capacity: Capacity) -> Inner<u32> {
+        Inner {
+            queue: VecDeque::new(),
+            waker: None,
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    #[test]
+    fn unbounded_always_enqueues() {
+        let mut inner = inner(Capacity::Unbounded);
+
+        for msg in 0..10 {
+            enqueue(&mut inner, msg);
+        }
+
+        assert_eq!(inner.queue.len(), 10);
+        assert_eq!(inner.dropped, 0);
+    }
+
+    #[test]
+    fn bounded_enqueues_under_limit() {
+        let mut inner = inner(Capacity::Bounded { limit: 3, overflow: Overflow::DropNewest });
+
+        enqueue(&mut inner, 1);
+        enqueue(&mut inner, 2);
+
+        assert_eq!(inner.queue, VecDeque::from([1, 2]));
+        assert_eq!(inner.dropped, 0);
+    }
+
+    #[test]
+    fn bounded_enqueues_at_limit() {
+        let mut inner = inner(Capacity::Bounded { limit: 2, overflow: Overflow::DropNewest });
+
+        enqueue(&mut inner, 1);
+        enqueue(&mut inner, 2);
+
+        assert_eq!(inner.queue, VecDeque::from([1, 2]));
+        assert_eq!(inner.dropped, 0);
+    }
+
+    #[test]
+    fn bounded_drop_oldest_evicts_front_over_limit() {
+        let mut inner = inner(Capacity::Bounded { limit: 2, overflow: Overflow::DropOldest });
+
+        enqueue(&mut inner, 1);
+        enqueue(&mut inner, 2);
+        enqueue(&mut inner, 3);
+
+        assert_eq!(inner.queue, VecDeque::from([2, 3]));
+        assert_eq!(inner.dropped, 1);
+    }
+
+    #[test]
+    fn bounded_drop_newest_discards_incoming_over_limit() {
+        let mut inner = inner(Capacity::Bounded { limit: 2, overflow: Overflow::DropNewest });
+
+        enqueue(&mut inner, 1);
+        enqueue(&mut inner, 2);
+        enqueue(&mut inner, 3);
+
+        assert_eq!(inner.queue, VecDeque::from([1, 2]));
+        assert_eq!(inner.dropped, 1);
+    }
+
+    #[test]
+    fn bounded_zero_limit_drop_oldest_never_queues_anything() {
+        let mut inner = inner(Capacity::Bounded { limit: 0, overflow: Overflow::DropOldest });
+
+        enqueue(&mut inner, 1);
+        enqueue(&mut inner, 2);
+
+        assert!(inner.queue.is_empty());
+        assert_eq!(inner.dropped, 2);
+    }
+
+    #[test]
+    fn bounded_zero_limit_drop_newest_never_queues_anything() {
+        let mut inner = inner(Capacity::Bounded { limit: 0, overflow: Overflow::DropNewest });
+
+        enqueue(&mut inner, 1);
+
+        assert!(inner.queue.is_empty());
+        assert_eq!(inner.dropped, 1);
+    }
+}
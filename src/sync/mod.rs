@@ -0,0 +1,12 @@
+//! Stream-based wrappers around Win32 notification sources.
+
+mod channel;
+mod message_window;
+mod signals;
+
+pub use channel::{channel, Receiver, Sender};
+pub use message_window::{Capacity, FromMessage, MessageWindow, Overflow};
+pub use signals::{CtrlEvent, Signals};
+
+pub(crate) use signals::dispatch as dispatch_signal;
+pub(crate) use signals::signal_message_id;
@@ -0,0 +1,277 @@
+//! Delivers console control events (Ctrl-C, Ctrl-Break, window close, etc)
+//! as an async [`Stream`], so an app can run graceful shutdown with
+//! `while let Some(sig) = signals.next().await`.
+//!
+//! `SetConsoleCtrlHandler` invokes its handler routine on a separate OS
+//! thread, so the handler can't touch a [`Signals`]' queue directly.
+//! Instead it marshals the event to every thread that has a live `Signals`
+//! as a registered thread message (the same trick `ExecutorHandle::spawn`
+//! uses to hand futures to the executor), and each thread's `block_on` loop dispatches
+//! it to every `Signals` stream registered on that thread.
+//!
+//! `SetConsoleCtrlHandler` installs one handler for the whole process, so a
+//! `Signals` on one thread does not stop another thread's `Signals` from
+//! receiving events: both are tracked and fanned out to independently.
+
+use core::cell::{Cell, RefCell};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use futures::Stream;
+use widestring::u16cstr;
+
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::wincon::{
+    SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT, CTRL_LOGOFF_EVENT,
+    CTRL_SHUTDOWN_EVENT,
+};
+use winapi::um::winuser::{PostThreadMessageW, RegisterWindowMessageW};
+
+/// A console control event delivered by [`Signals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtrlEvent {
+    C,
+    Break,
+    Close,
+    Logoff,
+    Shutdown,
+}
+
+impl CtrlEvent {
+    fn from_raw(ctrl_type: DWORD) -> Option<Self> {
+        match ctrl_type {
+            CTRL_C_EVENT => Some(CtrlEvent::C),
+            CTRL_BREAK_EVENT => Some(CtrlEvent::Break),
+            CTRL_CLOSE_EVENT => Some(CtrlEvent::Close),
+            CTRL_LOGOFF_EVENT => Some(CtrlEvent::Logoff),
+            CTRL_SHUTDOWN_EVENT => Some(CtrlEvent::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+struct Inner {
+    queue: VecDeque<CtrlEvent>,
+    waker: Option<core::task::Waker>,
+}
+
+thread_local! {
+    // Every live `Signals` on this thread, so a single console ctrl event
+    // can be fanned out to all of them.
+    static REGISTRY: RefCell<Vec<Weak<RefCell<Inner>>>> = RefCell::new(Vec::new());
+    // Live `Signals` on this thread specifically, so this thread's id can be
+    // added to/removed from `TARGET_THREADS` exactly once.
+    static HANDLER_COUNT: Cell<usize> = Cell::new(0);
+}
+
+// Every thread id that currently has at least one live `Signals`, so
+// `console_ctrl_handler` can fan an event out to all of them rather than
+// just one. `SetConsoleCtrlHandler` only ever gives us a plain function
+// pointer, so this has to be a global rather than something captured in a
+// closure.
+static TARGET_THREADS: OnceLock<Mutex<Vec<u32>>> = OnceLock::new();
+
+// Process-wide count of live `Signals`, across all threads, used to decide
+// when to install/remove the console ctrl handler. `SetConsoleCtrlHandler`
+// has no concept of "install per-thread" -- it's one handler for the whole
+// process -- so this has to track every thread's `Signals` together, unlike
+// `HANDLER_COUNT` which only tracks the calling thread's.
+static TOTAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn target_threads() -> &'static Mutex<Vec<u32>> {
+    TARGET_THREADS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// A stream of console control events (Ctrl-C, Ctrl-Break, console window
+/// close, logoff, shutdown).
+pub struct Signals {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Signals {
+    pub fn new() -> Self {
+        let inner = Rc::new(RefCell::new(Inner {
+            queue: VecDeque::new(),
+            waker: None,
+        }));
+
+        REGISTRY.with(|registry| registry.borrow_mut().push(Rc::downgrade(&inner)));
+
+        let was_registered_on_thread = HANDLER_COUNT.with(|count| {
+            let was_registered = count.get() > 0;
+            count.set(count.get() + 1);
+            was_registered
+        });
+
+        if !was_registered_on_thread {
+            target_threads().lock().unwrap().push(unsafe { GetCurrentThreadId() });
+        }
+
+        if TOTAL_COUNT.fetch_add(1, Ordering::SeqCst) == 0 {
+            let rc = unsafe { SetConsoleCtrlHandler(Some(console_ctrl_handler), TRUE) };
+            if rc != 1 {
+                let error = unsafe { GetLastError() };
+                panic!("SetConsoleCtrlHandler failed: {error}");
+            }
+        }
+
+        Signals { inner }
+    }
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for Signals {
+    type Item = CtrlEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<CtrlEvent>> {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(event) = inner.queue.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        inner.waker.replace(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        HANDLER_COUNT.with(|count| {
+            let remaining = count.get() - 1;
+            count.set(remaining);
+
+            if remaining == 0 {
+                let thread_id = unsafe { GetCurrentThreadId() };
+                target_threads().lock().unwrap().retain(|&id| id != thread_id);
+            }
+        });
+
+        if TOTAL_COUNT.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let rc = unsafe { SetConsoleCtrlHandler(Some(console_ctrl_handler), FALSE) };
+            if rc != 1 {
+                let error = unsafe { GetLastError() };
+                log::debug!("SetConsoleCtrlHandler deregister failed: {error}");
+            }
+        }
+    }
+}
+
+unsafe extern "system" fn console_ctrl_handler(ctrl_type: DWORD) -> BOOL {
+    let msg_id = signal_message_id();
+
+    for thread_id in target_threads().lock().unwrap().iter() {
+        let rc = PostThreadMessageW(*thread_id, msg_id, ctrl_type as _, 0);
+
+        if rc != 1 {
+            let error = GetLastError();
+            log::debug!("PostThreadMessageW failed forwarding console ctrl event: {error}");
+        }
+    }
+
+    TRUE
+}
+
+pub(crate) fn signal_message_id() -> u32 {
+    static MSG_ID: OnceLock<u32> = OnceLock::new();
+
+    *MSG_ID.get_or_init(|| {
+        let name = u16cstr!("windows_executor::sync::signals");
+        unsafe { RegisterWindowMessageW(name.as_ptr()) }
+    })
+}
+
+/// Called from `block_on`'s loop when it sees `signal_message_id()`, with
+/// the raw `ctrl_type` carried in the message's `wParam`.
+pub(crate) fn dispatch(ctrl_type: DWORD) {
+    let Some(event) = CtrlEvent::from_raw(ctrl_type) else {
+        return;
+    };
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+
+        registry.retain(|weak| {
+            let Some(inner) = weak.upgrade() else {
+                return false;
+            };
+
+            let mut inner = inner.borrow_mut();
+            inner.queue.push_back(event);
+
+            if let Some(waker) = inner.waker.take() {
+                waker.wake();
+            }
+
+            true
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reset_registry() {
+        REGISTRY.with(|registry| registry.borrow_mut().clear());
+    }
+
+    fn fresh_inner() -> Rc<RefCell<Inner>> {
+        let inner = Rc::new(RefCell::new(Inner {
+            queue: VecDeque::new(),
+            waker: None,
+        }));
+
+        REGISTRY.with(|registry| registry.borrow_mut().push(Rc::downgrade(&inner)));
+
+        inner
+    }
+
+    #[test]
+    fn ctrl_event_from_raw_maps_known_values() {
+        assert_eq!(CtrlEvent::from_raw(CTRL_C_EVENT), Some(CtrlEvent::C));
+        assert_eq!(CtrlEvent::from_raw(CTRL_BREAK_EVENT), Some(CtrlEvent::Break));
+        assert_eq!(CtrlEvent::from_raw(CTRL_CLOSE_EVENT), Some(CtrlEvent::Close));
+        assert_eq!(CtrlEvent::from_raw(CTRL_LOGOFF_EVENT), Some(CtrlEvent::Logoff));
+        assert_eq!(CtrlEvent::from_raw(CTRL_SHUTDOWN_EVENT), Some(CtrlEvent::Shutdown));
+        assert_eq!(CtrlEvent::from_raw(9999), None);
+    }
+
+    #[test]
+    fn dispatch_fans_out_to_every_registered_inner_on_this_thread() {
+        reset_registry();
+
+        let a = fresh_inner();
+        let b = fresh_inner();
+
+        dispatch(CTRL_C_EVENT);
+
+        assert_eq!(a.borrow_mut().queue.pop_front(), Some(CtrlEvent::C));
+        assert_eq!(b.borrow_mut().queue.pop_front(), Some(CtrlEvent::C));
+    }
+
+    #[test]
+    fn dispatch_prunes_entries_whose_inner_has_been_dropped() {
+        reset_registry();
+
+        let live = fresh_inner();
+        drop(fresh_inner());
+
+        dispatch(CTRL_C_EVENT);
+
+        assert_eq!(REGISTRY.with(|registry| registry.borrow().len()), 1);
+        assert_eq!(live.borrow_mut().queue.pop_front(), Some(CtrlEvent::C));
+    }
+}
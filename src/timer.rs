@@ -0,0 +1,67 @@
+use core::cell::{Cell, RefCell};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::executor;
+
+/// Waits until `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Waits until `deadline` has passed.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        registration: None,
+    }
+}
+
+struct Registration {
+    cancelled: Rc<Cell<bool>>,
+    waker: Rc<RefCell<Waker>>,
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`].
+pub struct Sleep {
+    deadline: Instant,
+    registration: Option<Registration>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let this = self.get_mut();
+
+        match &this.registration {
+            Some(registration) => {
+                *registration.waker.borrow_mut() = cx.waker().clone();
+            }
+            None => {
+                let (cancelled, waker) = executor::schedule_timer(this.deadline, cx.waker().clone());
+                this.registration = Some(Registration { cancelled, waker });
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        // If we never fired, mark our heap entry as cancelled so the
+        // executor prunes it instead of waking a task that's gone away.
+        if let Some(registration) = &self.registration {
+            registration.cancelled.set(true);
+        }
+    }
+}
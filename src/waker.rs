@@ -24,13 +24,21 @@ pub unsafe fn new(thread: HANDLE) -> Waker {
 }
 
 pub unsafe fn new_raw(thread: HANDLE) -> RawWaker {
-    let mut handle = ptr::null_mut();
+    RawWaker::new(duplicate_handle(thread) as *const (), &VTABLE)
+}
+
+/// Duplicates `handle` so the copy outlives the thread that created it and
+/// stays valid (and the thread id it identifies un-recycled) for as long as
+/// the caller holds onto it. The caller is responsible for eventually
+/// passing the returned handle to `CloseHandle`.
+pub(crate) unsafe fn duplicate_handle(handle: HANDLE) -> HANDLE {
+    let mut duplicated = ptr::null_mut();
 
     let rc = DuplicateHandle(
         GetCurrentProcess(),
-        thread,
+        handle,
         GetCurrentProcess(),
-        &mut handle,
+        &mut duplicated,
         0,
         FALSE,
         DUPLICATE_SAME_ACCESS,
@@ -41,7 +49,7 @@ pub unsafe fn new_raw(thread: HANDLE) -> RawWaker {
         panic!("DuplicateHandle failed: {error}");
     }
 
-    RawWaker::new(handle as *const (), &VTABLE)
+    duplicated
 }
 
 unsafe fn clone(ptr: *const ()) -> RawWaker {